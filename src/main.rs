@@ -1,17 +1,43 @@
 use std::path::Path;
 use clap::{ Arg, app_from_crate, crate_authors, crate_description, crate_name, crate_version, value_t_or_exit };
-use indicatif::{ ProgressBar, ProgressIterator, ProgressStyle };
-use image;
+use rayon::prelude::*;
+use indicatif::{ ProgressBar, ProgressStyle };
 use image::RgbImage;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Position(u32, u32);
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Energy {
+    Backward,
+    Forward,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorSpace {
+    Rgb,
+    Lab,
+}
+
+// Energy offset applied to protected pixels, pushing them far out of reach of
+// any seam. Removal marks are handled separately via `Seam::touches_remove`,
+// which guarantees a mask-touching seam always wins regardless of its raw
+// cost, rather than relying on a flat offset that large enough energies could
+// swamp.
+const PROTECT_BIAS: u32 = 100_000;
+
 #[derive(Clone, Copy, Debug)]
 struct Seam {
     posn: Position,
     prev_posn: Option<Position>,
     cost: u32,
+    touches_remove: bool,
+}
+
+/// Rank a seam for selection: seams touching the removal mask always outrank
+/// seams that don't, regardless of cost; ties within a tier break on cost.
+fn seam_rank(seam: &Seam) -> (bool, u32) {
+    (!seam.touches_remove, seam.cost)
 }
 
 fn get_von_neumann_neighbors(img: &RgbImage, x: u32, y: u32) -> Vec<Position> {
@@ -38,7 +64,78 @@ fn get_von_neumann_neighbors(img: &RgbImage, x: u32, y: u32) -> Vec<Position> {
     neighbors
 }
 
-fn calculate_energy(img: &RgbImage, x: u32, y: u32) -> u32 {
+fn transpose(img: &RgbImage) -> RgbImage {
+    let mut transposed: RgbImage = RgbImage::new(img.height(), img.width());
+    for y in 0..img.height() {
+        for x in 0..img.width() {
+            transposed.put_pixel(y, x, *img.get_pixel(x, y));
+        }
+    }
+    transposed
+}
+
+/// Convert an sRGB pixel to CIE L\*a\*b\* using the standard D65 white point.
+fn srgb_to_lab(pixel: &image::Rgb<u8>) -> [f32; 3] {
+    let linearize = |c: u8| -> f32 {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let r = linearize(pixel[0]);
+    let g = linearize(pixel[1]);
+    let b = linearize(pixel[2]);
+
+    // Linear sRGB to XYZ (D65).
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+
+    // Normalize by the D65 white point.
+    let f = |t: f32| -> f32 {
+        if t > 0.008856 {
+            t.powf(1.0 / 3.0)
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let fx = f(x / 0.95047);
+    let fy = f(y / 1.0);
+    let fz = f(z / 1.08883);
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn generate_lab_vector(img: &RgbImage) -> Vec<[f32; 3]> {
+    let mut lab: Vec<[f32; 3]> = Vec::new();
+
+    for y in 0..img.height() {
+        for x in 0..img.width() {
+            lab.push(srgb_to_lab(img.get_pixel(x, y)));
+        }
+    }
+
+    lab
+}
+
+/// Sum of squared channel differences between a pixel and its von Neumann
+/// neighbors. When `lab` is supplied the differences are taken over the cached
+/// L\*a\*b\* values rather than raw sRGB channels.
+fn calculate_energy(img: &RgbImage, x: u32, y: u32, lab: Option<&[[f32; 3]]>) -> u32 {
+    if let Some(lab) = lab {
+        let mut energy: f32 = 0.0;
+        let center = lab[(x + y * img.width()) as usize];
+        for neighbor_position in get_von_neumann_neighbors(img, x, y) {
+            let neighbor = lab[(neighbor_position.0 + neighbor_position.1 * img.width()) as usize];
+            for channel in 0..3 {
+                energy += (neighbor[channel] - center[channel]).powi(2);
+            }
+        }
+        return energy as u32;
+    }
+
     let mut energy: u32 = 0;
     let center_pixel = img.get_pixel(x, y);
 
@@ -52,16 +149,20 @@ fn calculate_energy(img: &RgbImage, x: u32, y: u32) -> u32 {
     energy
 }
 
-fn generate_energies_vector(img: &RgbImage) -> Vec<u32> {
-    let mut energies: Vec<u32> = Vec::new();
-
-    for y in 0..img.height() {
-        for x in 0..img.width() {
-            energies.push(calculate_energy(img, x, y));
-        }
-    }
+fn generate_energies_vector(img: &RgbImage, color_space: ColorSpace) -> Vec<u32> {
+    let lab = match color_space {
+        ColorSpace::Lab => Some(generate_lab_vector(img)),
+        ColorSpace::Rgb => None,
+    };
 
-    energies
+    // Energy is a pure per-pixel function, so computing the whole map is
+    // embarrassingly parallel. Collecting by index keeps the result
+    // deterministic regardless of the configured thread count.
+    (0..img.width() * img.height()).into_par_iter().map(|i| {
+        let x = i % img.width();
+        let y = i / img.width();
+        calculate_energy(img, x, y, lab.as_deref())
+    }).collect()
 }
 
 fn get_bottom_up_neighbors(img: &RgbImage, x: u32, y: u32) -> Vec<Position> {
@@ -73,43 +174,98 @@ fn get_bottom_up_neighbors(img: &RgbImage, x: u32, y: u32) -> Vec<Position> {
     neighbors.push(Position(x, y - 1));
     if x == 0 {
         neighbors.push(Position(x + 1, y - 1));
-        return neighbors;
     } else if x == img.width() - 1 {
         neighbors.push(Position(x - 1, y - 1));
-        return neighbors;
     } else {
         neighbors.push(Position(x + 1, y - 1));
         neighbors.push(Position(x - 1, y - 1));
-        return neighbors;
+    }
+
+    neighbors
+}
+
+fn get_clamped_pixel(img: &RgbImage, x: i64, y: u32) -> image::Rgb<u8> {
+    let clamped_x = x.max(0).min(img.width() as i64 - 1) as u32;
+    *img.get_pixel(clamped_x, y)
+}
+
+fn abs_pixel_difference(a: &image::Rgb<u8>, b: &image::Rgb<u8>) -> u32 {
+    (a[0] as i32 - b[0] as i32).unsigned_abs()
+        + (a[1] as i32 - b[1] as i32).unsigned_abs()
+        + (a[2] as i32 - b[2] as i32).unsigned_abs()
+}
+
+/// Forward-energy transition cost of reaching `(x, y)` from the predecessor at
+/// `prev_x` in the row above, measuring the energy *introduced* by removing the
+/// seam pixel rather than the energy of the pixel itself. Image borders are
+/// handled by clamping the neighbor lookups.
+fn forward_transition_cost(img: &RgbImage, x: u32, y: u32, prev_x: u32) -> u32 {
+    let left = get_clamped_pixel(img, x as i64 - 1, y);
+    let right = get_clamped_pixel(img, x as i64 + 1, y);
+    let up = get_clamped_pixel(img, x as i64, y - 1);
+
+    let c_u = abs_pixel_difference(&left, &right);
+    if prev_x < x {
+        c_u + abs_pixel_difference(&up, &left)
+    } else if prev_x > x {
+        c_u + abs_pixel_difference(&up, &right)
+    } else {
+        c_u
     }
 }
 
-fn generate_bottom_up_vector(img: &RgbImage, energies: &Vec<u32>) -> Vec<Seam> {
-    let mut bottom_up: Vec<Seam> = Vec::new();
+/// Step cost of moving from `prev_x` in the row above onto `(x, y)`: the
+/// configured energy function plus any additive protect-mask bias, applied
+/// uniformly so protection is honored under forward energy too.
+fn step_cost(img: &RgbImage, energies: &[u32], bias: Option<&[u32]>, energy: Energy, x: u32, y: u32, prev_x: u32) -> u32 {
+    let base = match energy {
+        Energy::Backward => energies[(x + y * img.width()) as usize],
+        Energy::Forward => forward_transition_cost(img, x, y, prev_x),
+    };
+    let offset = bias.map_or(0, |bias| bias[(x + y * img.width()) as usize]);
+    base.saturating_add(offset)
+}
+
+fn generate_bottom_up_vector(img: &RgbImage, energies: &[u32], bias: Option<&[u32]>, remove: Option<&[bool]>, energy: Energy) -> Vec<Seam> {
+    let mut bottom_up: Vec<Seam> = Vec::with_capacity((img.width() * img.height()) as usize);
 
     // Base case
     for x in 0..img.width() {
-        bottom_up.push(Seam{ posn: Position(x, 0), prev_posn: None, cost: energies[x as usize] });
+        let idx = x as usize;
+        let cost = energies[idx].saturating_add(bias.map_or(0, |bias| bias[idx]));
+        let touches_remove = remove.is_some_and(|remove| remove[idx]);
+        bottom_up.push(Seam{ posn: Position(x, 0), prev_posn: None, cost, touches_remove });
     }
 
-    // Recursive case
+    // Recursive case. Rows carry a dependency on the row above, but the cells
+    // within a single row are independent, so each row is filled in parallel
+    // while the rows themselves are processed in order.
     for y in 1..img.height() {
-        for x in 0..img.width() {
-            let prev_posn = *get_bottom_up_neighbors(img, x, y).iter().min_by_key(|posn| bottom_up[(posn.0 + posn.1 * img.width()) as usize].cost).unwrap();
-            let cost = energies[(x + y * img.width()) as usize] + bottom_up[(prev_posn.0 + prev_posn.1 * img.width()) as usize].cost;
-            bottom_up.push(Seam{ posn: Position(x, y), prev_posn: Some(prev_posn), cost });
-        }
+        let row: Vec<Seam> = {
+            let bottom_up = &bottom_up;
+            (0..img.width()).into_par_iter().map(|x| {
+                let idx = (x + y * img.width()) as usize;
+                let marked = remove.is_some_and(|remove| remove[idx]);
+                let (prev_posn, prev_cost, prev_touches) = get_bottom_up_neighbors(img, x, y).iter().map(|posn| {
+                    let predecessor = &bottom_up[(posn.0 + posn.1 * img.width()) as usize];
+                    let step = step_cost(img, energies, bias, energy, x, y, posn.0);
+                    (*posn, predecessor.cost + step, predecessor.touches_remove)
+                }).min_by_key(|(_, cost, touches)| (!*touches, *cost)).unwrap();
+                Seam{ posn: Position(x, y), prev_posn: Some(prev_posn), cost: prev_cost, touches_remove: marked || prev_touches }
+            }).collect()
+        };
+        bottom_up.extend(row);
     }
 
     bottom_up
 }
 
-fn determine_best_seam<'a>(img: &RgbImage, bottom_up: &'a Vec<Seam>) -> &'a Seam {
-    // Return Seam with lowest cost
-    bottom_up[((img.width() * (img.height() - 1)) as usize)..].iter().min_by_key(|seam| seam.cost).unwrap()
+fn determine_best_seam<'a>(img: &RgbImage, bottom_up: &'a [Seam]) -> &'a Seam {
+    // A seam touching the removal mask always wins; otherwise lowest cost wins.
+    bottom_up[((img.width() * (img.height() - 1)) as usize)..].iter().min_by_key(|seam| seam_rank(seam)).unwrap()
 }
 
-fn seam_to_position_vector(img: &RgbImage, bottom_up: &Vec<Seam>, initial_seam: &Seam) -> Vec<Position> {
+fn seam_to_position_vector(img: &RgbImage, bottom_up: &[Seam], initial_seam: &Seam) -> Vec<Position> {
     let mut seam = initial_seam;
     let mut posn_vector = Vec::new();
     posn_vector.push(seam.posn);
@@ -121,17 +277,17 @@ fn seam_to_position_vector(img: &RgbImage, bottom_up: &Vec<Seam>, initial_seam:
     posn_vector
 }
 
-fn cut_seam(old_img: RgbImage, bottom_up: &Vec<Seam>) -> RgbImage {
+fn cut_seam(old_img: RgbImage, bottom_up: &[Seam]) -> RgbImage {
     // Determine best seam
-    let seam = determine_best_seam(&old_img, &bottom_up);
-    let posns_to_remove = seam_to_position_vector(&old_img, &bottom_up, &seam);
+    let seam = determine_best_seam(&old_img, bottom_up);
+    let posns_to_remove = seam_to_position_vector(&old_img, bottom_up, seam);
 
     // Create new image
     let mut new_img: RgbImage = RgbImage::new(old_img.width() - 1, old_img.height());
     for (y, posn_to_remove) in (0..old_img.height()).zip(posns_to_remove) {
         let mut new_x = 0;
         for old_x in 0..old_img.width() {
-            if !(old_x == posn_to_remove.0) {
+            if old_x != posn_to_remove.0 {
                 new_img.put_pixel(new_x, y, *old_img.get_pixel(old_x, y));
                 new_x += 1;
             }
@@ -141,6 +297,335 @@ fn cut_seam(old_img: RgbImage, bottom_up: &Vec<Seam>) -> RgbImage {
     new_img
 }
 
+/// Remove the pixels in `posns` (one per row) from the image and its cached
+/// energy/Lab buffers. Lab values are per-pixel, so surviving entries merely
+/// shift; energies are shifted too and then recomputed only for the columns
+/// immediately adjacent to each cut, whose neighborhoods actually changed.
+fn apply_vertical_cut(
+    img: &RgbImage,
+    energies: &[u32],
+    lab: Option<&[[f32; 3]]>,
+    posns: &[Position],
+) -> (RgbImage, Vec<u32>, Option<Vec<[f32; 3]>>) {
+    let width = img.width();
+    let height = img.height();
+    let new_width = width - 1;
+
+    let mut new_img: RgbImage = RgbImage::new(new_width, height);
+    let mut new_energies: Vec<u32> = vec![0; (new_width * height) as usize];
+    let mut new_lab: Option<Vec<[f32; 3]>> = lab.map(|_| vec![[0.0; 3]; (new_width * height) as usize]);
+
+    for y in 0..height {
+        let remove_x = posns[y as usize].0;
+        let mut new_x = 0;
+        for old_x in 0..width {
+            if old_x == remove_x {
+                continue;
+            }
+            new_img.put_pixel(new_x, y, *img.get_pixel(old_x, y));
+            new_energies[(new_x + y * new_width) as usize] = energies[(old_x + y * width) as usize];
+            if let (Some(source), Some(dest)) = (lab, new_lab.as_mut()) {
+                dest[(new_x + y * new_width) as usize] = source[(old_x + y * width) as usize];
+            }
+            new_x += 1;
+        }
+    }
+
+    // Only the columns bordering the removed pixel have a changed neighborhood.
+    for y in 0..height {
+        let remove_x = posns[y as usize].0;
+        let first = remove_x.saturating_sub(1);
+        let last = remove_x.min(new_width - 1);
+        for new_x in first..=last {
+            new_energies[(new_x + y * new_width) as usize] = calculate_energy(&new_img, new_x, y, new_lab.as_deref());
+        }
+    }
+
+    (new_img, new_energies, new_lab)
+}
+
+/// Remove the pixels in `posns` from a single-channel mask, keeping it aligned
+/// with the image as seams are carved away.
+fn carve_mask(mask: &[bool], posns: &[Position], width: u32, height: u32) -> Vec<bool> {
+    let new_width = width - 1;
+    let mut new_mask: Vec<bool> = vec![false; (new_width * height) as usize];
+    for y in 0..height {
+        let remove_x = posns[y as usize].0;
+        let mut new_x = 0;
+        for old_x in 0..width {
+            if old_x == remove_x {
+                continue;
+            }
+            new_mask[(new_x + y * new_width) as usize] = mask[(old_x + y * width) as usize];
+            new_x += 1;
+        }
+    }
+    new_mask
+}
+
+/// Additive per-pixel offset that steers seams away from a protected mask.
+/// Returns `None` when there is nothing to protect.
+fn protect_bias(protect: &Option<Vec<bool>>) -> Option<Vec<u32>> {
+    let protect = protect.as_ref()?;
+    let mut bias = vec![0u32; protect.len()];
+    for (offset, protected) in bias.iter_mut().zip(protect) {
+        if *protected {
+            *offset = PROTECT_BIAS;
+        }
+    }
+    Some(bias)
+}
+
+fn mask_has_marks(mask: &Option<Vec<bool>>) -> bool {
+    match mask {
+        Some(mask) => mask.iter().any(|&marked| marked),
+        None => false,
+    }
+}
+
+/// Carve `count` vertical seams, reusing the energy and Lab buffers across
+/// iterations via `apply_vertical_cut` instead of rebuilding them each time.
+/// An optional protect mask adds an energy bias that steers seams away from
+/// its marked pixels; an optional remove mask makes seams touching its marked
+/// pixels outrank all others. Both masks are carried along with the image.
+fn carve_vertical_seams(mut img: RgbImage, count: u32, energy: Energy, color_space: ColorSpace, mut protect: Option<Vec<bool>>, mut remove: Option<Vec<bool>>, progress_bar: &ProgressBar) -> RgbImage {
+    let mut energies = generate_energies_vector(&img, color_space);
+    let mut lab = match color_space {
+        ColorSpace::Lab => Some(generate_lab_vector(&img)),
+        ColorSpace::Rgb => None,
+    };
+
+    for _ in 0..count {
+        let bias = protect_bias(&protect);
+        let bottom_up = generate_bottom_up_vector(&img, &energies, bias.as_deref(), remove.as_deref(), energy);
+        let seam = determine_best_seam(&img, &bottom_up);
+        let posns = seam_to_position_vector(&img, &bottom_up, seam);
+
+        let (width, height) = (img.width(), img.height());
+        if let Some(mask) = &protect {
+            protect = Some(carve_mask(mask, &posns, width, height));
+        }
+        if let Some(mask) = &remove {
+            remove = Some(carve_mask(mask, &posns, width, height));
+        }
+
+        let (new_img, new_energies, new_lab) = apply_vertical_cut(&img, &energies, lab.as_deref(), &posns);
+        img = new_img;
+        energies = new_energies;
+        lab = new_lab;
+        progress_bar.inc(1);
+    }
+
+    img
+}
+
+fn carve_horizontal_seams(img: RgbImage, count: u32, energy: Energy, color_space: ColorSpace, progress_bar: &ProgressBar) -> RgbImage {
+    transpose(&carve_vertical_seams(transpose(&img), count, energy, color_space, None, None, progress_bar))
+}
+
+/// Carve seams, preferring ones that touch the removal mask, until no marked
+/// pixels remain, then optionally enlarge back to the original width so the
+/// canvas size is preserved and the masked object simply disappears.
+///
+/// Fails instead of panicking if the image is carved down to a single column
+/// while marks remain, since `get_von_neumann_neighbors` requires at least two
+/// columns to find a horizontal neighbor.
+fn remove_object(mut img: RgbImage, energy: Energy, color_space: ColorSpace, mut protect: Option<Vec<bool>>, mut remove: Option<Vec<bool>>, restore_width: bool, progress_bar: &ProgressBar) -> Result<RgbImage, String> {
+    let original_width = img.width();
+
+    if img.width() <= 1 && mask_has_marks(&remove) {
+        return Err(format!("Cannot remove the marked region: it spans the full width of a {}-pixel-wide image", original_width));
+    }
+
+    let mut energies = generate_energies_vector(&img, color_space);
+    let mut lab = match color_space {
+        ColorSpace::Lab => Some(generate_lab_vector(&img)),
+        ColorSpace::Rgb => None,
+    };
+
+    while mask_has_marks(&remove) {
+        if img.width() <= 1 {
+            return Err(format!("Cannot remove the marked region: it spans the full width of a {}-pixel-wide image", original_width));
+        }
+
+        let bias = protect_bias(&protect);
+        let bottom_up = generate_bottom_up_vector(&img, &energies, bias.as_deref(), remove.as_deref(), energy);
+        let seam = determine_best_seam(&img, &bottom_up);
+        let posns = seam_to_position_vector(&img, &bottom_up, seam);
+
+        let (width, height) = (img.width(), img.height());
+        if let Some(mask) = &protect {
+            protect = Some(carve_mask(mask, &posns, width, height));
+        }
+        if let Some(mask) = &remove {
+            remove = Some(carve_mask(mask, &posns, width, height));
+        }
+
+        let (new_img, new_energies, new_lab) = apply_vertical_cut(&img, &energies, lab.as_deref(), &posns);
+        img = new_img;
+        energies = new_energies;
+        lab = new_lab;
+        progress_bar.inc(1);
+    }
+
+    if restore_width && img.width() < original_width {
+        let columns_to_add = original_width - img.width();
+        img = enlarge_vertical(img, columns_to_add, energy, color_space);
+    }
+
+    Ok(img)
+}
+
+fn load_mask(path: &str, img: &RgbImage) -> Vec<bool> {
+    let mask = image::open(Path::new(path)).unwrap().into_luma8();
+    assert!(mask.width() == img.width() && mask.height() == img.height(), "Mask dimensions must match the input image");
+
+    let mut marks: Vec<bool> = Vec::with_capacity((img.width() * img.height()) as usize);
+    for y in 0..img.height() {
+        for x in 0..img.width() {
+            marks.push(mask.get_pixel(x, y)[0] > 127);
+        }
+    }
+    marks
+}
+
+fn determine_best_seams<'a>(img: &RgbImage, bottom_up: &'a [Seam], k: usize) -> Vec<&'a Seam> {
+    // Return the `k` lowest-cost seams by their bottom-row end positions.
+    let mut bottom_row: Vec<&Seam> = bottom_up[((img.width() * (img.height() - 1)) as usize)..].iter().collect();
+    bottom_row.sort_by_key(|seam| seam.cost);
+    bottom_row.into_iter().take(k).collect()
+}
+
+fn insert_seams(old_img: &RgbImage, bottom_up: &[Seam], seams: &[&Seam]) -> RgbImage {
+    // Collect, per row, the columns touched by the chosen seams. Each seam
+    // contributes exactly one column per row, so every row grows by `seams.len()`.
+    let mut marks: Vec<Vec<u32>> = vec![Vec::new(); old_img.height() as usize];
+    for seam in seams {
+        for posn in seam_to_position_vector(old_img, bottom_up, seam) {
+            marks[posn.1 as usize].push(posn.0);
+        }
+    }
+
+    let mut new_img: RgbImage = RgbImage::new(old_img.width() + seams.len() as u32, old_img.height());
+    for y in 0..old_img.height() {
+        let mut new_x = 0;
+        for old_x in 0..old_img.width() {
+            let pixel = *old_img.get_pixel(old_x, y);
+            new_img.put_pixel(new_x, y, pixel);
+            new_x += 1;
+
+            // Duplicate each marked pixel, averaging it with its right neighbor.
+            let duplicates = marks[y as usize].iter().filter(|&&c| c == old_x).count();
+            if duplicates > 0 {
+                let neighbor = if old_x + 1 < old_img.width() {
+                    *old_img.get_pixel(old_x + 1, y)
+                } else {
+                    pixel
+                };
+                let mut averaged = pixel;
+                for channel in 0..3 {
+                    averaged[channel] = ((pixel[channel] as u32 + neighbor[channel] as u32) / 2) as u8;
+                }
+                for _ in 0..duplicates {
+                    new_img.put_pixel(new_x, y, averaged);
+                    new_x += 1;
+                }
+            }
+        }
+    }
+
+    new_img
+}
+
+fn enlarge_vertical(img: RgbImage, k: u32, energy: Energy, color_space: ColorSpace) -> RgbImage {
+    let k = (k as usize).min(img.width() as usize);
+    let energies = generate_energies_vector(&img, color_space);
+    let bottom_up = generate_bottom_up_vector(&img, &energies, None, None, energy);
+    let seams = determine_best_seams(&img, &bottom_up, k);
+    insert_seams(&img, &bottom_up, &seams)
+}
+
+fn enlarge_horizontal(img: RgbImage, k: u32, energy: Energy, color_space: ColorSpace) -> RgbImage {
+    transpose(&enlarge_vertical(transpose(&img), k, energy, color_space))
+}
+
+fn carve_vertical_seam(img: RgbImage, energy: Energy, color_space: ColorSpace) -> RgbImage {
+    let energies = generate_energies_vector(&img, color_space);
+    let bottom_up = generate_bottom_up_vector(&img, &energies, None, None, energy);
+    cut_seam(img, &bottom_up)
+}
+
+fn carve_horizontal_seam(img: RgbImage, energy: Energy, color_space: ColorSpace) -> RgbImage {
+    // A horizontal seam in the image is a vertical seam in its transpose.
+    transpose(&carve_vertical_seam(transpose(&img), energy, color_space))
+}
+
+fn best_vertical_seam_cost(img: &RgbImage, energy: Energy, color_space: ColorSpace) -> u32 {
+    let energies = generate_energies_vector(img, color_space);
+    let bottom_up = generate_bottom_up_vector(img, &energies, None, None, energy);
+    determine_best_seam(img, &bottom_up).cost
+}
+
+fn best_horizontal_seam_cost(img: &RgbImage, energy: Energy, color_space: ColorSpace) -> u32 {
+    best_vertical_seam_cost(&transpose(img), energy, color_space)
+}
+
+/// Carve `horizontal` rows and `vertical` columns, choosing the order of
+/// removals that minimises total seam energy via a transport-map DP.
+///
+/// `t[r][c]` is the minimum total seam energy to remove `r` horizontal and `c`
+/// vertical seams; `from_horizontal[r][c]` records whether the cheapest way to
+/// reach that cell ended on a horizontal removal. Because each transition needs
+/// the actual carved image to measure its best seam, we carry the intermediate
+/// images forward one DP row at a time and return the image sitting at the
+/// optimal corner `t[horizontal][vertical]`.
+fn retarget(img: RgbImage, horizontal: u32, vertical: u32, energy: Energy, color_space: ColorSpace, progress_bar: &ProgressBar) -> RgbImage {
+    let rows = horizontal as usize;
+    let cols = vertical as usize;
+
+    let mut t = vec![vec![0u32; cols + 1]; rows + 1];
+    let mut from_horizontal = vec![vec![false; cols + 1]; rows + 1];
+
+    // Base row `r = 0`: only vertical seams have been removed so far.
+    let mut prev_row: Vec<RgbImage> = Vec::with_capacity(cols + 1);
+    prev_row.push(img);
+    progress_bar.inc(1);
+    for c in 1..=cols {
+        t[0][c] = t[0][c - 1] + best_vertical_seam_cost(&prev_row[c - 1], energy, color_space);
+        let carved = carve_vertical_seam(prev_row[c - 1].clone(), energy, color_space);
+        prev_row.push(carved);
+        progress_bar.inc(1);
+    }
+
+    for r in 1..=rows {
+        let mut cur_row: Vec<RgbImage> = Vec::with_capacity(cols + 1);
+
+        // `c = 0`: only horizontal seams can lead here.
+        t[r][0] = t[r - 1][0] + best_horizontal_seam_cost(&prev_row[0], energy, color_space);
+        from_horizontal[r][0] = true;
+        cur_row.push(carve_horizontal_seam(prev_row[0].clone(), energy, color_space));
+        progress_bar.inc(1);
+
+        for c in 1..=cols {
+            let from_top = t[r - 1][c] + best_horizontal_seam_cost(&prev_row[c], energy, color_space);
+            let from_left = t[r][c - 1] + best_vertical_seam_cost(&cur_row[c - 1], energy, color_space);
+            if from_top <= from_left {
+                t[r][c] = from_top;
+                from_horizontal[r][c] = true;
+                cur_row.push(carve_horizontal_seam(prev_row[c].clone(), energy, color_space));
+            } else {
+                t[r][c] = from_left;
+                cur_row.push(carve_vertical_seam(cur_row[c - 1].clone(), energy, color_space));
+            }
+            progress_bar.inc(1);
+        }
+
+        prev_row = cur_row;
+    }
+
+    prev_row.pop().unwrap()
+}
+
 fn main() {
     // Print banner
     println!(r"                                  _     _      ");
@@ -153,10 +638,37 @@ fn main() {
 
     // Parse arguments
     let matches = app_from_crate!()
-        .arg(Arg::with_name("percentage")
+        .arg(Arg::with_name("width_percent")
+            .long("width-percent")
             .short("p")
             .default_value("66")
-            .help("Percentage of image to scrunch"))
+            .help("Target width as a percentage of the source"))
+        .arg(Arg::with_name("height_percent")
+            .long("height-percent")
+            .default_value("100")
+            .help("Target height as a percentage of the source"))
+        .arg(Arg::with_name("energy")
+            .long("energy")
+            .default_value("backward")
+            .possible_values(&["backward", "forward"])
+            .help("Energy function used to score seams"))
+        .arg(Arg::with_name("color_space")
+            .long("color-space")
+            .default_value("rgb")
+            .possible_values(&["rgb", "lab"])
+            .help("Color space in which energy is measured"))
+        .arg(Arg::with_name("threads")
+            .long("threads")
+            .default_value("0")
+            .help("Worker threads to use (0 = all cores)"))
+        .arg(Arg::with_name("protect")
+            .long("protect")
+            .takes_value(true)
+            .help("Mask of regions to steer seams away from (used only when carving columns alone or removing an object)"))
+        .arg(Arg::with_name("remove")
+            .long("remove")
+            .takes_value(true)
+            .help("Mask of regions to carve away (object removal)"))
         .arg(Arg::with_name("input_file")
             .required(true)
             .help("Input image path"))
@@ -166,24 +678,101 @@ fn main() {
         .get_matches();
     let input_file = matches.value_of("input_file").unwrap();
     let output_file = matches.value_of("output_file").unwrap();
-    let percentage = value_t_or_exit!(matches.value_of("percentage"), u32);
+    let width_percent = value_t_or_exit!(matches.value_of("width_percent"), u32);
+    let height_percent = value_t_or_exit!(matches.value_of("height_percent"), u32);
+    let energy = match matches.value_of("energy").unwrap() {
+        "forward" => Energy::Forward,
+        _ => Energy::Backward,
+    };
+    let color_space = match matches.value_of("color_space").unwrap() {
+        "lab" => ColorSpace::Lab,
+        _ => ColorSpace::Rgb,
+    };
+
+    // forward_transition_cost only ever measures raw sRGB channels, so pairing
+    // it with --color-space lab would silently fall back to RGB for every row
+    // but the first (whose base case still uses the Lab-aware backward
+    // energy), giving a result inconsistent with what either flag claims.
+    if energy == Energy::Forward && color_space == ColorSpace::Lab {
+        eprintln!("--energy forward does not support --color-space lab");
+        std::process::exit(1);
+    }
+
+    let threads = value_t_or_exit!(matches.value_of("threads"), usize);
+
+    // A thread count of 0 leaves rayon's default (all cores) in place; any other
+    // value sizes the global pool, with 1 giving deterministic single-threaded runs.
+    if threads != 0 {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().unwrap();
+    }
 
     // Load image and print details
-    let mut img = image::open(Path::new(input_file)).unwrap().into_rgb();
+    let mut img = image::open(Path::new(input_file)).unwrap().into_rgb8();
     println!("Source Resolution: {} x {} ({} pixels)", img.width(), img.height(), img.width() * img.height());
-    let columns_to_carve = img.width() * percentage / 100;
-    println!("Columns To Carve: {} ({}%)", columns_to_carve, percentage);
+
+    let protect = matches.value_of("protect").map(|path| load_mask(path, &img));
+    let remove = matches.value_of("remove").map(|path| load_mask(path, &img));
+
+    // Object removal: carve away the marked region, then restore the width.
+    if remove.is_some() {
+        println!("Removing masked region");
+        println!();
+        let progress_bar = ProgressBar::new_spinner();
+        match remove_object(img, energy, color_space, protect, remove, true, &progress_bar) {
+            Ok(result) => {
+                progress_bar.finish();
+                result.save(Path::new(output_file)).unwrap();
+            }
+            Err(message) => {
+                progress_bar.finish_and_clear();
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let target_width = img.width() * width_percent / 100;
+    let target_height = img.height() * height_percent / 100;
+    println!("Target Resolution: {} x {} ({}% x {}%)", target_width, target_height, width_percent, height_percent);
     println!();
 
-    // Seam carving
-    let progress_bar = ProgressBar::new(columns_to_carve as u64);
-    progress_bar.set_style(ProgressStyle::default_bar()
-        .template("[{elapsed_precise}] [{bar:40}] {pos}/{len} ")
-        .progress_chars("#>-"));
-    for _ in (0..columns_to_carve).progress_with(progress_bar) {
-        let energies = generate_energies_vector(&img);
-        let bottom_up = generate_bottom_up_vector(&img, &energies);
-        img = cut_seam(img, &bottom_up);
+    // Seam insertion grows each dimension independently; shrinking both at once
+    // is handled by the transport-map retargeter.
+    if target_width > img.width() {
+        let columns_to_add = target_width - img.width();
+        img = enlarge_vertical(img, columns_to_add, energy, color_space);
+    }
+    if target_height > img.height() {
+        let rows_to_add = target_height - img.height();
+        img = enlarge_horizontal(img, rows_to_add, energy, color_space);
+    }
+
+    let columns_to_carve = img.width().saturating_sub(target_width);
+    let rows_to_carve = img.height().saturating_sub(target_height);
+    if columns_to_carve > 0 && rows_to_carve > 0 {
+        // Shrinking both dimensions needs the transport-map interleaving.
+        let progress_bar = ProgressBar::new(((columns_to_carve + 1) * (rows_to_carve + 1)) as u64);
+        progress_bar.set_style(ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40}] {pos}/{len} ")
+            .progress_chars("#>-"));
+        img = retarget(img, rows_to_carve, columns_to_carve, energy, color_space, &progress_bar);
+        progress_bar.finish();
+    } else if columns_to_carve > 0 {
+        // Single-dimension carves reuse the energy map incrementally.
+        let progress_bar = ProgressBar::new(columns_to_carve as u64);
+        progress_bar.set_style(ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40}] {pos}/{len} ")
+            .progress_chars("#>-"));
+        img = carve_vertical_seams(img, columns_to_carve, energy, color_space, protect, None, &progress_bar);
+        progress_bar.finish();
+    } else if rows_to_carve > 0 {
+        let progress_bar = ProgressBar::new(rows_to_carve as u64);
+        progress_bar.set_style(ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40}] {pos}/{len} ")
+            .progress_chars("#>-"));
+        img = carve_horizontal_seams(img, rows_to_carve, energy, color_space, &progress_bar);
+        progress_bar.finish();
     }
 
     // Save image
@@ -196,7 +785,7 @@ mod tests {
     use image::Rgb;
 
     fn open_test_image() -> RgbImage {
-        image::open(Path::new("/home/sam/projects/classes/H343/h343/L7/balloon-sky.jpg")).unwrap().into_rgb()
+        image::open(Path::new("/home/sam/projects/classes/H343/h343/L7/balloon-sky.jpg")).unwrap().into_rgb8()
     }
 
     #[test]
@@ -263,21 +852,21 @@ mod tests {
     #[test]
     fn test_calculate_energy() {
         let img = open_test_image();
-        assert_eq!(calculate_energy(&img, 748, 28), 20);
-        assert_eq!(calculate_energy(&img, 406, 59), 84);
-        assert_eq!(calculate_energy(&img, 462, 92), 39);
-        assert_eq!(calculate_energy(&img, 332, 101), 0);
-        assert_eq!(calculate_energy(&img, 602, 237), 96);
-        assert_eq!(calculate_energy(&img, 34, 387), 7);
-        assert_eq!(calculate_energy(&img, 673, 394), 0);
-        assert_eq!(calculate_energy(&img, 213, 397), 6);
-        assert_eq!(calculate_energy(&img, 63, 442), 84);
-        assert_eq!(calculate_energy(&img, 388, 510), 16);
-        assert_eq!(calculate_energy(&img, 899, 535), 0);
-        assert_eq!(calculate_energy(&img, 689, 546), 27);
-        assert_eq!(calculate_energy(&img, 359, 599), 26);
-        assert_eq!(calculate_energy(&img, 4, 629), 23);
-        assert_eq!(calculate_energy(&img, 53, 673), 0);
+        assert_eq!(calculate_energy(&img, 748, 28, None), 20);
+        assert_eq!(calculate_energy(&img, 406, 59, None), 84);
+        assert_eq!(calculate_energy(&img, 462, 92, None), 39);
+        assert_eq!(calculate_energy(&img, 332, 101, None), 0);
+        assert_eq!(calculate_energy(&img, 602, 237, None), 96);
+        assert_eq!(calculate_energy(&img, 34, 387, None), 7);
+        assert_eq!(calculate_energy(&img, 673, 394, None), 0);
+        assert_eq!(calculate_energy(&img, 213, 397, None), 6);
+        assert_eq!(calculate_energy(&img, 63, 442, None), 84);
+        assert_eq!(calculate_energy(&img, 388, 510, None), 16);
+        assert_eq!(calculate_energy(&img, 899, 535, None), 0);
+        assert_eq!(calculate_energy(&img, 689, 546, None), 27);
+        assert_eq!(calculate_energy(&img, 359, 599, None), 26);
+        assert_eq!(calculate_energy(&img, 4, 629, None), 23);
+        assert_eq!(calculate_energy(&img, 53, 673, None), 0);
     }
 
     #[test]
@@ -301,14 +890,174 @@ mod tests {
         img.put_pixel(3, 2, blue);
         img.put_pixel(4, 2, red);
 
-        let energies = generate_energies_vector(&img);
-        let bottom_up = generate_bottom_up_vector(&img, &energies);
+        let energies = generate_energies_vector(&img, ColorSpace::Rgb);
+        let bottom_up = generate_bottom_up_vector(&img, &energies, None, None, Energy::Backward);
         let seam = determine_best_seam(&img, &bottom_up);
-        let posns_to_remove = seam_to_position_vector(&img, &bottom_up, &seam);
+        let posns_to_remove = seam_to_position_vector(&img, &bottom_up, seam);
         assert_eq!(posns_to_remove.len(), 3);
         assert_eq!(seam.cost, 0);
         assert_eq!(posns_to_remove[0], Position(2, 0));
         assert_eq!(posns_to_remove[1], Position(2, 1));
         assert_eq!(posns_to_remove[2], Position(2, 2));
     }
+
+    fn gray(value: u8) -> Rgb<u8> {
+        Rgb([value, 0, 0])
+    }
+
+    #[test]
+    fn test_forward_transition_cost() {
+        let mut img = image::RgbImage::new(3, 2);
+        img.put_pixel(0, 0, gray(10));
+        img.put_pixel(1, 0, gray(20));
+        img.put_pixel(2, 0, gray(40));
+        img.put_pixel(0, 1, gray(50));
+        img.put_pixel(1, 1, gray(80));
+        img.put_pixel(2, 1, gray(100));
+
+        // Interior cell (1, 1): C_U = |100 - 50| = 50.
+        assert_eq!(forward_transition_cost(&img, 1, 1, 1), 50);
+        // Left diagonal adds |up - left| = |20 - 50| = 30.
+        assert_eq!(forward_transition_cost(&img, 1, 1, 0), 80);
+        // Right diagonal adds |up - right| = |20 - 100| = 80.
+        assert_eq!(forward_transition_cost(&img, 1, 1, 2), 130);
+
+        // Border cell (0, 1) clamps the missing left neighbor to itself:
+        // C_U = |80 - 50| = 30, right diagonal adds |10 - 80| = 70.
+        assert_eq!(forward_transition_cost(&img, 0, 1, 0), 30);
+        assert_eq!(forward_transition_cost(&img, 0, 1, 1), 100);
+    }
+
+    #[test]
+    fn test_protect_bias() {
+        let protect = Some(vec![false, true, false, true]);
+        let bias = protect_bias(&protect).unwrap();
+        assert_eq!(bias, vec![0, PROTECT_BIAS, 0, PROTECT_BIAS]);
+
+        assert!(protect_bias(&None).is_none());
+    }
+
+    #[test]
+    fn test_carve_mask() {
+        let mask = vec![
+            true, false, false,
+            false, true, false,
+        ];
+        let posns = vec![Position(1, 0), Position(0, 1)];
+        let carved = carve_mask(&mask, &posns, 3, 2);
+        assert_eq!(carved, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn test_mask_has_marks() {
+        assert!(!mask_has_marks(&None));
+        assert!(!mask_has_marks(&Some(vec![false, false])));
+        assert!(mask_has_marks(&Some(vec![false, true])));
+    }
+
+    #[test]
+    fn test_remove_object_converges_through_high_contrast_mark() {
+        // The marked column is far higher energy than its neighbors; a
+        // magnitude-based bias could be swamped by it, but `touches_remove`
+        // guarantees its seam is chosen regardless.
+        let mut img = RgbImage::new(4, 2);
+        for y in 0..2 {
+            img.put_pixel(0, y, Rgb([10, 10, 10]));
+            img.put_pixel(1, y, Rgb([12, 12, 12]));
+            img.put_pixel(2, y, Rgb([255, 255, 255]));
+            img.put_pixel(3, y, Rgb([14, 14, 14]));
+        }
+
+        let remove = Some(vec![false, false, true, false, false, false, true, false]);
+        let progress_bar = ProgressBar::hidden();
+        let result = remove_object(img, Energy::Backward, ColorSpace::Rgb, None, remove, false, &progress_bar).unwrap();
+        assert!(result.width() < 4);
+    }
+
+    #[test]
+    fn test_remove_object_errors_before_panicking_on_uncarvable_mask() {
+        let mut img = RgbImage::new(1, 1);
+        img.put_pixel(0, 0, Rgb([200, 50, 50]));
+
+        let remove = Some(vec![true]);
+        let progress_bar = ProgressBar::hidden();
+        let result = remove_object(img, Energy::Backward, ColorSpace::Rgb, None, remove, false, &progress_bar);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_srgb_to_lab() {
+        let close = |actual: [f32; 3], expected: [f32; 3]| {
+            for channel in 0..3 {
+                assert!(
+                    (actual[channel] - expected[channel]).abs() < 0.05,
+                    "channel {}: {} != {}", channel, actual[channel], expected[channel]
+                );
+            }
+        };
+
+        close(srgb_to_lab(&Rgb([255, 255, 255])), [100.0, 0.0, 0.0]);
+        close(srgb_to_lab(&Rgb([0, 0, 0])), [0.0, 0.0, 0.0]);
+        close(srgb_to_lab(&Rgb([255, 0, 0])), [53.24, 80.09, 67.20]);
+    }
+
+    #[test]
+    fn test_insert_seams_averages_neighbors() {
+        let mut img = image::RgbImage::new(2, 1);
+        img.put_pixel(0, 0, Rgb([0, 0, 0]));
+        img.put_pixel(1, 0, Rgb([255, 255, 255]));
+
+        // Insert the single-column seam sitting on the black pixel. The inserted
+        // column is the average of that pixel and its right (white) neighbor.
+        let seam = Seam{ posn: Position(0, 0), prev_posn: None, cost: 0, touches_remove: false };
+        let enlarged = insert_seams(&img, &[], &[&seam]);
+
+        assert_eq!(enlarged.width(), 3);
+        assert_eq!(*enlarged.get_pixel(0, 0), Rgb([0, 0, 0]));
+        assert_eq!(*enlarged.get_pixel(1, 0), Rgb([127, 127, 127]));
+        assert_eq!(*enlarged.get_pixel(2, 0), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_retarget_output_dimensions() {
+        let mut img = image::RgbImage::new(5, 4);
+        for y in 0..4 {
+            for x in 0..5 {
+                img.put_pixel(x, y, Rgb([(x * 40) as u8, (y * 50) as u8, ((x + y) * 20) as u8]));
+            }
+        }
+
+        // Removing 1 horizontal and 2 vertical seams shrinks the image by one
+        // row and two columns regardless of the interleaving chosen by the DP.
+        let result = retarget(img, 1, 2, Energy::Backward, ColorSpace::Rgb, &ProgressBar::hidden());
+        assert_eq!(result.width(), 3);
+        assert_eq!(result.height(), 3);
+    }
+
+    #[test]
+    fn test_apply_vertical_cut_matches_full_rebuild() {
+        let mut img = image::RgbImage::new(5, 4);
+        for y in 0..4 {
+            for x in 0..5 {
+                img.put_pixel(x, y, Rgb([(x * 40) as u8, (y * 50) as u8, ((x + y) * 20) as u8]));
+            }
+        }
+
+        // The incrementally patched energy map must be identical to rebuilding
+        // the whole map from the carved image, in both colour spaces.
+        let energies = generate_energies_vector(&img, ColorSpace::Rgb);
+        let bottom_up = generate_bottom_up_vector(&img, &energies, None, None, Energy::Backward);
+        let seam = determine_best_seam(&img, &bottom_up);
+        let posns = seam_to_position_vector(&img, &bottom_up, seam);
+
+        let (new_img, new_energies, _) = apply_vertical_cut(&img, &energies, None, &posns);
+        assert_eq!(new_energies, generate_energies_vector(&new_img, ColorSpace::Rgb));
+
+        let lab = generate_lab_vector(&img);
+        let lab_energies = generate_energies_vector(&img, ColorSpace::Lab);
+        let (lab_img, lab_new_energies, new_lab) =
+            apply_vertical_cut(&img, &lab_energies, Some(&lab), &posns);
+        assert_eq!(lab_new_energies, generate_energies_vector(&lab_img, ColorSpace::Lab));
+        assert_eq!(new_lab, Some(generate_lab_vector(&lab_img)));
+    }
 }